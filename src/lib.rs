@@ -1,61 +1,2371 @@
-use chumsky::prelude::*;
+//! Without the default `report` feature the parser only needs `alloc`, and
+//! the crate builds as `no_std`.
+
+#![cfg_attr(not(feature = "report"), no_std)]
+
+extern crate alloc;
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+#[cfg(feature = "report")]
+use ariadne::{Color, Config, Fmt, Label, Report, ReportKind, Source};
+use chumsky::{error::SimpleReason, prelude::*};
+use core::{fmt, ops::Range};
+
+pub mod typecheck;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum VariableScope {
+    Let,
+    Const,
+    /// Function-scoped and hoisted: visible (as `undefined`) before its
+    /// declaration.
+    Var,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UnOp {
+    Neg,
+    Not,
+}
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Instruction {
     StringLiteral(String),
-    NumericLiteral(f32),
+    IntLiteral(i64),
+    FloatLiteral(f64),
+    BooleanLiteral(bool),
     FunctionCall {
         name: String,
-        args: Vec<Instruction>,
+        args: Vec<Spanned<Instruction>>,
+    },
+    /// A `...value` call argument, expanded into positional arguments.
+    Spread(Box<Spanned<Instruction>>),
+    Variable {
+        scope: VariableScope,
+        name: String,
+        value: Box<Spanned<Instruction>>,
+    },
+    VariableReference(String),
+    Array(Vec<Spanned<Instruction>>),
+    Object(BTreeMap<String, Spanned<Instruction>>),
+    Assign {
+        name: String,
+        value: Box<Spanned<Instruction>>,
+    },
+    BinaryOp {
+        op: BinOp,
+        lhs: Box<Spanned<Instruction>>,
+        rhs: Box<Spanned<Instruction>>,
+    },
+    UnaryOp {
+        op: UnOp,
+        operand: Box<Spanned<Instruction>>,
+    },
+    Index {
+        target: Box<Spanned<Instruction>>,
+        index: Box<Spanned<Instruction>>,
+    },
+    Member {
+        target: Box<Spanned<Instruction>>,
+        field: String,
+    },
+    /// Comma-separated values, evaluated left to right, yielding the last.
+    Sequence(Vec<Spanned<Instruction>>),
+    IfElse {
+        condition: Box<Spanned<Instruction>>,
+        then_body: Vec<Spanned<Instruction>>,
+        else_body: Vec<Spanned<Instruction>>,
+    },
+    WhileBlock {
+        condition: Box<Spanned<Instruction>>,
+        body: Vec<Spanned<Instruction>>,
+    },
+    FunctionDef {
+        name: String,
+        params: Vec<String>,
+        body: Vec<Spanned<Instruction>>,
+    },
+    Return(Option<Box<Spanned<Instruction>>>),
+    Throw(Box<Spanned<Instruction>>),
+    Break,
+    Continue,
+    ForBlock {
+        init: Box<Spanned<Instruction>>,
+        condition: Box<Spanned<Instruction>>,
+        update: Box<Spanned<Instruction>>,
+        body: Vec<Spanned<Instruction>>,
     },
+    TryCatch {
+        body: Vec<Spanned<Instruction>>,
+        param: String,
+        handler: Vec<Spanned<Instruction>>,
+    },
+}
+
+/// A node and the span of source it was parsed from.
+///
+/// Spans are ignored by `==`, so the same program compares equal however it
+/// is laid out.
+#[derive(Clone)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Range<usize>,
 }
 
-pub fn str_literal() -> impl Parser<char, Instruction, Error = Simple<char>> {
-    let quote = choice((just('"'), just('\'')));
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Range<usize>) -> Self {
+        Spanned { node, span }
+    }
+}
 
-    quote
-        .ignore_then(filter(|c| *c != '"' && *c != '\'').repeated())
-        .then_ignore(quote)
-        .collect::<String>()
+impl<T: PartialEq> PartialEq for Spanned<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Spanned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.node.fmt(f)?;
+        write!(f, " @ {:?}", self.span)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Spanned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.node.fmt(f)
+    }
+}
+
+impl fmt::Display for VariableScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            VariableScope::Let => "let",
+            VariableScope::Const => "const",
+            VariableScope::Var => "var",
+        })
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+        })
+    }
+}
+
+impl fmt::Display for UnOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            UnOp::Neg => "-",
+            UnOp::Not => "!",
+        })
+    }
+}
+
+/// An operand that is parenthesised if it is itself an operator expression,
+/// so printed code keeps the precedence it was parsed with.
+struct Operand<'a>(&'a Instruction);
+
+impl fmt::Display for Operand<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Instruction::Assign { .. }
+            | Instruction::BinaryOp { .. }
+            | Instruction::UnaryOp { .. } => write!(f, "({})", self.0),
+            value => write!(f, "{}", value),
+        }
+    }
+}
+
+fn join(values: &[Spanned<Instruction>]) -> String {
+    values
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn write_block(
+    f: &mut fmt::Formatter,
+    body: &[Spanned<Instruction>],
+) -> fmt::Result {
+    if body.is_empty() {
+        return f.write_str("{}");
+    }
+
+    f.write_str("{ ")?;
+    for statement in body {
+        write!(f, "{}; ", statement)?;
+    }
+    f.write_str("}")
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::StringLiteral(value) => write!(f, "{:?}", value),
+            Instruction::IntLiteral(value) => write!(f, "{}", value),
+            // Debug keeps the decimal point, so `2.0` doesn't print as `2`.
+            Instruction::FloatLiteral(value) => write!(f, "{:?}", value),
+            Instruction::BooleanLiteral(value) => write!(f, "{}", value),
+            Instruction::FunctionCall { name, args } => {
+                write!(f, "{}({})", name, join(args))
+            }
+            Instruction::Spread(value) => write!(f, "...{}", value),
+            Instruction::Variable { scope, name, value } => {
+                write!(f, "{} {} = {}", scope, name, value)
+            }
+            Instruction::VariableReference(name) => f.write_str(name),
+            Instruction::Array(values) => write!(f, "[{}]", join(values)),
+            Instruction::Object(fields) if fields.is_empty() => {
+                f.write_str("{}")
+            }
+            Instruction::Object(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(key, value)| {
+                        let is_ident = key
+                            .chars()
+                            .next()
+                            .is_some_and(|c| !c.is_ascii_digit())
+                            && key
+                                .chars()
+                                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+                        if is_ident {
+                            format!("{}: {}", key, value)
+                        } else {
+                            format!("{:?}: {}", key, value)
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            Instruction::Assign { name, value } => {
+                write!(f, "{} = {}", name, value)
+            }
+            Instruction::BinaryOp { op, lhs, rhs } => {
+                write!(
+                    f,
+                    "{} {} {}",
+                    Operand(&lhs.node),
+                    op,
+                    Operand(&rhs.node)
+                )
+            }
+            Instruction::UnaryOp { op, operand } => {
+                write!(f, "{}{}", op, Operand(&operand.node))
+            }
+            Instruction::Index { target, index } => {
+                write!(f, "{}[{}]", Operand(&target.node), index)
+            }
+            Instruction::Member { target, field } => {
+                write!(f, "{}.{}", Operand(&target.node), field)
+            }
+            Instruction::Sequence(values) => write!(f, "({})", join(values)),
+            Instruction::IfElse {
+                condition,
+                then_body,
+                else_body,
+            } => {
+                write!(f, "if ({}) ", condition)?;
+                write_block(f, then_body)?;
+
+                match else_body.as_slice() {
+                    [] => Ok(()),
+                    [Spanned {
+                        node: else_if @ Instruction::IfElse { .. },
+                        ..
+                    }] => {
+                        write!(f, " else {}", else_if)
+                    }
+                    body => {
+                        f.write_str(" else ")?;
+                        write_block(f, body)
+                    }
+                }
+            }
+            Instruction::WhileBlock { condition, body } => {
+                write!(f, "while ({}) ", condition)?;
+                write_block(f, body)
+            }
+            Instruction::FunctionDef { name, params, body } => {
+                write!(f, "function {}({}) ", name, params.join(", "))?;
+                write_block(f, body)
+            }
+            Instruction::Return(None) => f.write_str("return"),
+            Instruction::Return(Some(value)) => write!(f, "return {}", value),
+            Instruction::Throw(value) => write!(f, "throw {}", value),
+            Instruction::Break => f.write_str("break"),
+            Instruction::Continue => f.write_str("continue"),
+            Instruction::ForBlock {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                write!(f, "for ({}; {}; {}) ", init, condition, update)?;
+                write_block(f, body)
+            }
+            Instruction::TryCatch {
+                body,
+                param,
+                handler,
+            } => {
+                f.write_str("try ")?;
+                write_block(f, body)?;
+                write!(f, " catch ({}) ", param)?;
+                write_block(f, handler)
+            }
+        }
+    }
+}
+
+/// Whitespace and comments. Block comments don't nest; a `/*` inside one is
+/// reported as an error rather than silently ending at the first `*/`.
+pub fn whitespace() -> impl Parser<char, (), Error = Simple<char>> + Clone {
+    // Whitespace may appear almost anywhere, so it is tried and fails at the
+    // end of nearly every token. `filter` keeps those failures out of the
+    // "expected" list of whatever error is reported there, where `just('/')`
+    // would add a `/` to all of them.
+    let char = |c| filter(move |found: &char| *found == c);
+    let line_comment = char('/')
+        .then(char('/'))
+        .then(take_until(just('\n').ignored().or(end())))
+        .ignored();
+
+    let block_comment = char('/')
+        .then(char('*'))
+        .ignore_then(take_until(just("*/")))
+        .validate(|(body, _), span, emit| {
+            if body.windows(2).any(|w| w == ['/', '*']) {
+                emit(Simple::custom(
+                    span,
+                    "Nested block comments are not supported",
+                ));
+            }
+        });
+
+    choice((
+        filter(|c: &char| c.is_whitespace()).ignored(),
+        line_comment,
+        block_comment,
+    ))
+    .repeated()
+    .ignored()
+}
+
+/// `inner` between `open` and `close`. A missing `close` is reported as an
+/// unclosed delimiter but otherwise treated as present, so the statements
+/// around it still make it into the AST.
+fn delimited<O>(
+    open: char,
+    inner: impl Parser<char, O, Error = Simple<char>> + Clone,
+    close: char,
+) -> impl Parser<char, O, Error = Simple<char>> + Clone {
+    let missing = any()
+        .or_not()
+        .rewind()
+        .map_with_span(|found, span| Some((found, span)));
+
+    just(open)
+        .map_with_span(|_, span| span)
+        .then(inner)
+        .then(choice((just(close).to(None), missing)))
+        .validate(move |((open_span, inner), missing), _, emit| {
+            if let Some((found, span)) = missing {
+                emit(Simple::unclosed_delimiter(
+                    open_span, open, span, close, found,
+                ));
+            }
+            inner
+        })
+}
+
+pub fn string() -> impl Parser<char, String, Error = Simple<char>> + Clone {
+    let hex_digits = |n| {
+        filter(char::is_ascii_hexdigit)
+            .repeated()
+            .at_least(1)
+            .at_most(n)
+            .collect::<String>()
+    };
+    // `\xNN` covers U+0000 to U+00FF as in JS, so bytes above 0x7F become the
+    // Latin-1 character with that code point.
+    let code_point = choice((
+        just('x').ignore_then(hex_digits(2).validate(|digits, span, emit| {
+            if digits.len() != 2 {
+                emit(Simple::custom(span, "Expected two hex digits after \\x"));
+            }
+            digits
+        })),
+        just('u').ignore_then(hex_digits(6).delimited_by(just('{'), just('}'))),
+    ))
+    .validate(|digits, span, emit| {
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .unwrap_or_else(|| {
+                emit(Simple::custom(span, "Invalid character escape"));
+                char::REPLACEMENT_CHARACTER
+            })
+    });
+    let escape = just('\\').ignore_then(choice((
+        just('\\'),
+        just('"'),
+        just('\''),
+        just('n').to('\n'),
+        just('r').to('\r'),
+        just('t').to('\t'),
+        just('0').to('\0'),
+        code_point,
+    )));
+
+    // Only the quote a string was opened with closes it; the other one is an
+    // ordinary character inside it, as in `"it's"`.
+    let quoted = |quote: char| {
+        just(quote)
+            .ignore_then(
+                filter(move |c| *c != quote && *c != '\\')
+                    .or(escape)
+                    .repeated(),
+            )
+            .then_ignore(just(quote))
+    };
+
+    choice((quoted('"'), quoted('\''))).collect::<String>()
+}
+
+pub fn str_literal(
+) -> impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone {
+    string()
         .map(Instruction::StringLiteral)
+        .map_with_span(Spanned::new)
 }
 
-pub fn num_literal() -> impl Parser<char, Instruction, Error = Simple<char>> {
-    text::int(10)
-        .chain::<char, _, _>(
-            just('.').chain(text::digits(10)).or_not().flatten(),
-        )
+pub fn bool_literal(
+) -> impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone {
+    choice((keyword("true").to(true), keyword("false").to(false)))
+        .map(Instruction::BooleanLiteral)
+        .map_with_span(Spanned::new)
+}
+
+pub fn num_literal(
+) -> impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone {
+    let too_large = |span, emit: &mut dyn FnMut(Simple<char>)| {
+        emit(Simple::custom(span, "Integer literal is too large"));
+        i64::MAX
+    };
+
+    // `_` separators are accepted anywhere a digit is and checked afterwards,
+    // so that `1__0` is reported instead of ending the literal early.
+    let digit =
+        |radix: u32| filter(move |c: &char| c.is_digit(radix) || *c == '_');
+    let strip_separators =
+        |n: String,
+         radix: u32,
+         span: &Range<usize>,
+         emit: &mut dyn FnMut(Simple<char>)| {
+            let chars = n.chars().collect::<Vec<_>>();
+            let between_digits = |i: usize| {
+                i > 0
+                    && chars[i - 1].is_digit(radix)
+                    && chars.get(i + 1).is_some_and(|c| c.is_digit(radix))
+            };
+            if (0..chars.len()).any(|i| chars[i] == '_' && !between_digits(i)) {
+                emit(Simple::custom(
+                    span.clone(),
+                    "Numeric separators must be between two digits",
+                ));
+            }
+            n.replace('_', "")
+        };
+
+    // `0x` and `0b` literals are always integers.
+    let radix_int = move |prefix: &'static str, radix: u32| {
+        just('0')
+            .ignore_then(one_of(prefix))
+            .then(digit(radix).repeated().collect::<String>())
+            .validate(move |(prefix, digits), span, emit| {
+                let digits = strip_separators(digits, radix, &span, emit);
+                if digits.is_empty() {
+                    emit(Simple::custom(
+                        span,
+                        format!("Expected digits after 0{}", prefix),
+                    ));
+                    return Instruction::IntLiteral(0);
+                }
+                Instruction::IntLiteral(
+                    i64::from_str_radix(&digits, radix)
+                        .unwrap_or_else(|_| too_large(span, emit)),
+                )
+            })
+    };
+
+    // The digits are checked in `validate` so that `1e` is reported with a
+    // clearer message than a missing digit would give.
+    let exponent = one_of("eE")
+        .chain(one_of("+-").or_not())
+        .chain::<char, _, _>(digit(10).repeated());
+
+    // As in JS, the digits after the point may be left out before an
+    // exponent, as in `1.e3`, but not otherwise.
+    let fraction = just('.').chain::<char, _, _>(
+        digit(10)
+            .repeated()
+            .at_least(1)
+            .or(one_of("eE").rewind().to(Vec::new())),
+    );
+
+    let decimal = filter(char::is_ascii_digit)
+        .chain::<char, _, _>(digit(10).repeated())
+        .chain::<char, _, _>(fraction.or_not().flatten())
+        .chain::<char, _, _>(exponent.or_not().flatten())
         .collect::<String>()
-        .map(|n| Instruction::NumericLiteral(n.parse().unwrap()))
+        .validate(move |n, span, emit| {
+            let n = strip_separators(n, 10, &span, emit);
+            if n.contains(['.', 'e', 'E']) {
+                // Rounds to the nearest representable f64, like JS numbers.
+                // Only an exponent without digits fails to parse.
+                Instruction::FloatLiteral(match n.parse::<f64>() {
+                    Ok(value) if value.is_finite() => value,
+                    Ok(_) => {
+                        emit(Simple::custom(
+                            span,
+                            "Float literal is too large",
+                        ));
+                        f64::MAX
+                    }
+                    Err(_) => {
+                        emit(Simple::custom(
+                            span,
+                            "Expected digits in exponent",
+                        ));
+                        0.0
+                    }
+                })
+            } else {
+                Instruction::IntLiteral(
+                    n.parse().unwrap_or_else(|_| too_large(span, emit)),
+                )
+            }
+        });
+
+    // A literal running into a letter or another digit, as in `0b102` or
+    // `2px`, is reported rather than split into two values.
+    choice((radix_int("xX", 16), radix_int("bB", 2), decimal))
+        .then(
+            filter(|c: &char| c.is_alphanumeric() || *c == '_')
+                .repeated()
+                .collect::<String>(),
+        )
+        .validate(|(literal, rest), span, emit| {
+            if let Some(c) = rest.chars().next() {
+                emit(Simple::custom(
+                    span,
+                    format!("Invalid character `{}` in numeric literal", c),
+                ));
+            }
+            literal
+        })
+        .map_with_span(Spanned::new)
+}
+
+/// Words that can't name a variable, function or parameter: those with a
+/// meaning here, and those JS reserves, so that scripts stay valid JS.
+const KEYWORDS: &[&str] = &[
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "import",
+    "in",
+    "instanceof",
+    "let",
+    "new",
+    "null",
+    "return",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
+
+/// Like `text::keyword`, but another word fails where it starts rather than
+/// where it ends, so that a statement starting with an unexpected keyword is
+/// reported as such rather than as a mismatch with every statement keyword.
+fn keyword(
+    word: &'static str,
+) -> impl Parser<char, (), Error = Simple<char>> + Clone {
+    text::ident()
+        .rewind()
+        .try_map(move |ident: String, span| {
+            if ident == word {
+                Ok(())
+            } else {
+                Err(Simple::expected_input_found(span, None, None))
+            }
+        })
+        .ignore_then(text::ident().ignored())
+}
+
+/// The keywords that `value()` parses, so an expression may start with them.
+const EXPRESSION_KEYWORDS: &[&str] = &["await", "false", "true"];
+
+/// An identifier that isn't a reserved keyword. A keyword is reported but
+/// still accepted, so the rest of the statement parses as intended.
+pub fn identifier() -> impl Parser<char, String, Error = Simple<char>> + Clone {
+    text::ident().validate(|name: String, span, emit| {
+        if KEYWORDS.contains(&name.as_str()) {
+            emit(Simple::custom(
+                span,
+                format!("`{}` is a reserved keyword", name),
+            ));
+        }
+        name
+    })
+}
+
+/// The parenthesised, comma-separated arguments of a call.
+fn arguments(
+    value: impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone,
+) -> impl Parser<char, Vec<Spanned<Instruction>>, Error = Simple<char>> + Clone
+{
+    delimited(
+        '(',
+        just("...")
+            .ignore_then(whitespace())
+            .ignore_then(value.clone())
+            .map(|value| Instruction::Spread(Box::new(value)))
+            .map_with_span(Spanned::new)
+            .padded_by(whitespace())
+            .or(value)
+            .separated_by(just(','))
+            .allow_trailing()
+            .padded_by(whitespace()),
+        ')',
+    )
+}
+
+/// A call to a possibly dotted name such as `console.log(...)`. The dotted
+/// name is kept as a single `FunctionCall` name rather than a call on a
+/// `Member` target, so only names can be called, not values like `a[0].b`.
+pub fn fn_call(
+    value: impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone,
+) -> impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone {
+    // Only the first part is a variable; the rest are properties, which may
+    // be keywords as in JS.
+    identifier()
+        // Spaced like member access, so `console . log(1)` is still a call.
+        .then(
+            just('.')
+                .padded_by(whitespace())
+                .ignore_then(text::ident())
+                .repeated(),
+        )
+        .map(|(first, rest)| {
+            rest.iter().fold(first, |name, part| name + "." + part)
+        })
+        .padded_by(whitespace())
+        .then(arguments(value))
+        .map(|(ident, args)| Instruction::FunctionCall { name: ident, args })
+        .map_with_span(Spanned::new)
+}
+
+pub fn variable_reference(
+) -> impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone {
+    identifier()
+        .map(Instruction::VariableReference)
+        .map_with_span(Spanned::new)
 }
 
-pub fn fn_call() -> impl Parser<char, Instruction, Error = Simple<char>> {
-    recursive(|fn_call_parser| {
-        text::ident()
-            .separated_by(just('.'))
-            .map(|v| v.join("."))
-            .padded()
-            .then_ignore(just('('))
-            .padded()
+pub fn variable(
+    value: impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone,
+) -> impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone {
+    choice((
+        keyword("let").to(VariableScope::Let),
+        keyword("const").to(VariableScope::Const),
+        keyword("var").to(VariableScope::Var),
+    ))
+    .padded_by(whitespace())
+    .then(identifier())
+    .padded_by(whitespace())
+    .then_ignore(just('='))
+    .padded_by(whitespace())
+    .then(value)
+    .map_with_span(|((scope, name), value), span: Range<usize>| {
+        // Ends with the value, leaving out whitespace or a comment after it.
+        let span = span.start..value.span.end;
+        let value = Box::new(value);
+        Spanned::new(Instruction::Variable { scope, name, value }, span)
+    })
+}
+
+enum Postfix {
+    Index(Spanned<Instruction>),
+    Member(String),
+    /// Arguments passed to something other than a name, which is reported.
+    Call,
+}
+
+/// A binary operation spanning from the start of `lhs` to the end of `rhs`.
+fn binary_op(
+    lhs: Spanned<Instruction>,
+    op: BinOp,
+    rhs: Spanned<Instruction>,
+) -> Spanned<Instruction> {
+    let span = lhs.span.start..rhs.span.end;
+    Spanned::new(
+        Instruction::BinaryOp {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        },
+        span,
+    )
+}
+
+pub fn value(
+) -> impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone {
+    recursive(|value| {
+        let array = value
+            .clone()
+            .separated_by(just(','))
+            .allow_trailing()
+            .padded_by(whitespace());
+        let array = delimited('[', array, ']')
+            .map(Instruction::Array)
+            .map_with_span(Spanned::new);
+
+        let object = text::ident()
+            .or(string())
+            .padded_by(whitespace())
+            .then_ignore(just(':'))
+            .then(value.clone())
+            .separated_by(just(','))
+            .allow_trailing()
+            .padded_by(whitespace());
+        let object = delimited('{', object, '}')
+            .map(|fields| Instruction::Object(fields.into_iter().collect()))
+            .map_with_span(Spanned::new);
+
+        let atom = choice((
+            str_literal(),
+            num_literal(),
+            // Before `variable_reference`, which would accept `true` as a
+            // name.
+            bool_literal(),
+            array,
+            object,
+            fn_call(value.clone()),
+            variable_reference(),
+            // The comma operator is only accepted inside parentheses so that it
+            // can't be confused with the commas separating call arguments.
+            delimited(
+                '(',
+                value.clone().separated_by(just(',')).at_least(1),
+                ')',
+            )
+            .map_with_span(|mut values, span| {
+                if values.len() == 1 {
+                    values.remove(0)
+                } else {
+                    Spanned::new(Instruction::Sequence(values), span)
+                }
+            }),
+        ))
+        .padded_by(whitespace());
+
+        let postfix = atom
             .then(
-                choice((str_literal(), num_literal(), fn_call_parser))
-                    .separated_by(just(',')),
-            )
-            .padded()
-            .then_ignore(just(')'))
-            .map(|(ident, args)| Instruction::FunctionCall {
-                name: ident,
-                args,
+                choice((
+                    delimited('[', value.clone(), ']').map(Postfix::Index),
+                    just('.')
+                        .padded_by(whitespace())
+                        .ignore_then(text::ident())
+                        .map(Postfix::Member),
+                    // Rather than leaving the arguments to be parsed as a
+                    // parenthesised statement of their own.
+                    arguments(value.clone()).validate(|_, span, emit| {
+                        emit(Simple::custom(span, "Only names can be called"));
+                        Postfix::Call
+                    }),
+                ))
+                .map_with_span(|postfix, span: Range<usize>| {
+                    (postfix, span.end)
+                })
+                .padded_by(whitespace())
+                .repeated(),
+            )
+            .foldl(|target, (postfix, end)| {
+                let span = target.span.start..end;
+                let target = Box::new(target);
+                let node = match postfix {
+                    Postfix::Index(index) => Instruction::Index {
+                        target,
+                        index: Box::new(index),
+                    },
+                    Postfix::Member(field) => {
+                        Instruction::Member { target, field }
+                    }
+                    Postfix::Call => return *target,
+                };
+                Spanned::new(node, span)
+            });
+
+        // There is no async runtime: `await expr` evaluates `expr`
+        // synchronously, so the operand is used as-is.
+        let atom = keyword("await")
+            .padded_by(whitespace())
+            .repeated()
+            .ignore_then(postfix);
+
+        let unary = choice((just('-').to(UnOp::Neg), just('!').to(UnOp::Not)))
+            .map_with_span(|op, span: Range<usize>| (op, span.start))
+            .padded_by(whitespace())
+            .repeated()
+            .then(atom)
+            .foldr(|(op, start), operand| {
+                let span = start..operand.span.end;
+                let operand = Box::new(operand);
+                Spanned::new(Instruction::UnaryOp { op, operand }, span)
+            });
+
+        let product = unary
+            .clone()
+            .then(
+                choice((
+                    just('*').to(BinOp::Mul),
+                    just('/').to(BinOp::Div),
+                    just('%').to(BinOp::Mod),
+                ))
+                .then(unary)
+                .repeated(),
+            )
+            .foldl(|lhs, (op, rhs)| binary_op(lhs, op, rhs));
+
+        let sum = product
+            .clone()
+            .then(
+                choice((just('+').to(BinOp::Add), just('-').to(BinOp::Sub)))
+                    .then(product)
+                    .repeated(),
+            )
+            .foldl(|lhs, (op, rhs)| binary_op(lhs, op, rhs));
+
+        let comparison = sum
+            .clone()
+            .then(
+                choice((
+                    just("<=").to(BinOp::Le),
+                    just(">=").to(BinOp::Ge),
+                    just("==").to(BinOp::Eq),
+                    just("!=").to(BinOp::Ne),
+                    just('<').to(BinOp::Lt),
+                    just('>').to(BinOp::Gt),
+                ))
+                .then(sum)
+                .repeated(),
+            )
+            .validate(|(lhs, rest), span, emit| {
+                if rest.len() > 1 {
+                    emit(Simple::custom(
+                        span,
+                        "Comparison operators cannot be chained",
+                    ));
+                }
+
+                rest.into_iter()
+                    .fold(lhs, |lhs, (op, rhs)| binary_op(lhs, op, rhs))
+            });
+
+        // Assignment is right-associative and yields the assigned value, so
+        // `a = b = 5` assigns `5` to both. Parsing the target as an ordinary
+        // operand, rather than trying assignment as a separate alternative,
+        // keeps errors in the right-hand side from being masked by the
+        // target parsing successfully on its own.
+        comparison
+            .then(
+                // Don't mistake the left-hand side of `==` for an assignment.
+                just('=')
+                    .then(none_of('=').rewind())
+                    .padded_by(whitespace())
+                    .ignore_then(value)
+                    .or_not(),
+            )
+            .validate(|(target, value), span, emit| match (target, value) {
+                (target, None) => target,
+                (
+                    Spanned {
+                        node: Instruction::VariableReference(name),
+                        span: target_span,
+                    },
+                    Some(value),
+                ) => {
+                    let span = target_span.start..value.span.end;
+                    let value = Box::new(value);
+                    Spanned::new(Instruction::Assign { name, value }, span)
+                }
+                (target, Some(_)) => {
+                    emit(Simple::custom(span, "Invalid assignment target"));
+                    target
+                }
             })
     })
 }
 
-pub fn parser() -> impl Parser<char, Vec<Instruction>, Error = Simple<char>> {
-    recursive(|_parser| {
-        choice((str_literal(), num_literal(), fn_call()))
-            .then_ignore(just(';').or_not())
-            .padded()
-            .repeated()
+pub fn block(
+    statement: impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone,
+) -> impl Parser<char, Vec<Spanned<Instruction>>, Error = Simple<char>> + Clone
+{
+    whitespace()
+        .ignore_then(delimited(
+            '{',
+            statement.repeated().padded_by(whitespace()),
+            '}',
+        ))
+        .labelled("block")
+}
+
+pub fn if_else<'a>(
+    statement: impl Parser<char, Spanned<Instruction>, Error = Simple<char>>
+        + Clone
+        + 'a,
+) -> impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone + 'a
+{
+    recursive(|if_else| {
+        keyword("if")
+            .padded_by(whitespace())
+            .ignore_then(delimited('(', value(), ')'))
+            .then(block(statement.clone()))
+            .then(
+                keyword("else")
+                    .padded_by(whitespace())
+                    // `else if` chains nest as the sole statement of the
+                    // else branch.
+                    .ignore_then(block(statement).or(if_else.map(|i| vec![i])))
+                    .or_not(),
+            )
+            .map(|((condition, then_body), else_body)| Instruction::IfElse {
+                condition: Box::new(condition),
+                then_body,
+                else_body: else_body.unwrap_or_default(),
+            })
+            .map_with_span(Spanned::new)
+            .labelled("if statement")
     })
-    .then_ignore(end())
+}
+
+pub fn while_block(
+    statement: impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone,
+) -> impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone {
+    keyword("while")
+        .padded_by(whitespace())
+        .ignore_then(delimited('(', value(), ')'))
+        .then(block(statement))
+        .map(|(condition, body)| Instruction::WhileBlock {
+            condition: Box::new(condition),
+            body,
+        })
+        .map_with_span(Spanned::new)
+        .labelled("while loop")
+}
+
+pub fn function(
+    statement: impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone,
+) -> impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone {
+    // Like `await`, `async` is accepted but has no effect.
+    keyword("async")
+        .padded_by(whitespace())
+        .or_not()
+        .ignore_then(keyword("function"))
+        .padded_by(whitespace())
+        .ignore_then(identifier())
+        .padded_by(whitespace())
+        .then(delimited(
+            '(',
+            identifier()
+                .padded_by(whitespace())
+                .separated_by(just(','))
+                .allow_trailing()
+                .padded_by(whitespace()),
+            ')',
+        ))
+        .padded_by(whitespace())
+        .then(block(statement))
+        .map(|((name, params), body)| Instruction::FunctionDef {
+            name,
+            params,
+            body,
+        })
+        .map_with_span(Spanned::new)
+        .labelled("function definition")
+}
+
+pub fn for_block(
+    statement: impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone,
+) -> impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone {
+    keyword("for")
+        .padded_by(whitespace())
+        .ignore_then(delimited(
+            '(',
+            variable(value())
+                .or(value())
+                .then_ignore(just(';'))
+                .then(value())
+                .then_ignore(just(';'))
+                .then(value()),
+            ')',
+        ))
+        .then(block(statement))
+        .map(
+            |(((init, condition), update), body)| Instruction::ForBlock {
+                init: Box::new(init),
+                condition: Box::new(condition),
+                update: Box::new(update),
+                body,
+            },
+        )
+        .map_with_span(Spanned::new)
+        .labelled("for loop")
+}
+
+pub fn try_catch(
+    statement: impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone,
+) -> impl Parser<char, Spanned<Instruction>, Error = Simple<char>> + Clone {
+    keyword("try")
+        .padded_by(whitespace())
+        .ignore_then(block(statement.clone()))
+        .then_ignore(keyword("catch").padded_by(whitespace()))
+        .then(
+            delimited('(', identifier().padded_by(whitespace()), ')')
+                .padded_by(whitespace()),
+        )
+        .then(block(statement))
+        .map(|((body, param), handler)| Instruction::TryCatch {
+            body,
+            param,
+            handler,
+        })
+        .map_with_span(Spanned::new)
+        .labelled("try statement")
+}
+
+pub fn parser(
+) -> impl Parser<char, Vec<Spanned<Instruction>>, Error = Simple<char>> {
+    // Scripts may start with `#!/usr/bin/env trippy`; only the very first
+    // line is treated as a shebang.
+    let shebang = just("#!").then(filter(|c| *c != '\n').repeated()).or_not();
+
+    shebang
+        .ignore_then(
+            recursive(|statement| {
+                // As in JS, a statement starting with `{` is never an object
+                // literal; it has to be parenthesised, as in `({ a: 1 });`.
+                let expression = none_of('{').rewind().ignore_then(value());
+                // Without this, `function f() {` would also parse as a call to
+                // `function` and the error would point at the `{` instead of
+                // wherever the function definition itself went wrong.
+                //
+                // The error is raised one character in. That is past where
+                // the statements below fail on a word that isn't theirs, so it
+                // outranks those, but short of where one that matched its
+                // keyword fails, so `if {` still reports the missing `(`.
+                let not_keyword = text::ident()
+                    .or_not()
+                    .rewind()
+                    .then_ignore(any().or_not())
+                    .try_map(|ident, span: Range<usize>| {
+                        match ident.as_deref() {
+                            Some(ident)
+                                if KEYWORDS.contains(&ident)
+                                    && !EXPRESSION_KEYWORDS
+                                        .contains(&ident) =>
+                            {
+                                let end = span.start + ident.chars().count();
+                                Err(Simple::custom(
+                                    span.start..end,
+                                    "Unexpected keyword",
+                                ))
+                            }
+                            _ => Ok(()),
+                        }
+                    })
+                    .rewind();
+                let expression = not_keyword.ignore_then(expression);
+
+                // Like declarations, these end with their value rather than
+                // any whitespace or comment after it.
+                let return_ = keyword("return")
+                    .ignore_then(value().or_not())
+                    .map_with_span(|value, span: Range<usize>| {
+                        let span = match &value {
+                            Some(value) => span.start..value.span.end,
+                            None => span,
+                        };
+                        let node = Instruction::Return(value.map(Box::new));
+                        Spanned::new(node, span)
+                    });
+                let throw = keyword("throw")
+                    .ignore_then(value())
+                    .map_with_span(|value, span: Range<usize>| {
+                        let span = span.start..value.span.end;
+                        let node = Instruction::Throw(Box::new(value));
+                        Spanned::new(node, span)
+                    });
+                let jump = choice((
+                    keyword("break").to(Instruction::Break),
+                    keyword("continue").to(Instruction::Continue),
+                ))
+                .map_with_span(Spanned::new);
+
+                choice((
+                    if_else(statement.clone()),
+                    while_block(statement.clone()),
+                    for_block(statement.clone()),
+                    try_catch(statement.clone()),
+                    function(statement),
+                    variable(value()),
+                    return_,
+                    throw,
+                    jump,
+                    expression,
+                ))
+                .then_ignore(just(';').or_not())
+                .padded_by(whitespace())
+            })
+            .repeated()
+            // An empty or whitespace-only file is an empty program.
+            .padded_by(whitespace()),
+        )
+        .then_ignore(end())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// A statement at this index of the new program.
+    Added(usize),
+    /// A statement at this index of the old program.
+    Removed(usize),
+    Modified {
+        old: usize,
+        new: usize,
+    },
+}
+
+/// Reports which top-level statements differ between two parses.
+///
+/// Statements shared at the start and end of both programs are unchanged;
+/// the statements between them are paired up in order as modifications, and
+/// any left over on either side are reported as removed or added.
+pub fn ast_diff(
+    old: &[Spanned<Instruction>],
+    new: &[Spanned<Instruction>],
+) -> Vec<Change> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_end = old.len() - suffix;
+    let new_end = new.len() - suffix;
+    let paired = (old_end - prefix).min(new_end - prefix);
+
+    (0..paired)
+        .map(|i| Change::Modified {
+            old: prefix + i,
+            new: prefix + i,
+        })
+        .chain((prefix + paired..old_end).map(Change::Removed))
+        .chain((prefix + paired..new_end).map(Change::Added))
+        .collect()
+}
+
+/// Renders a program as a Graphviz DOT graph with one box per instruction,
+/// for viewing with `dot -Tpng`.
+pub fn to_dot(ast: &[Spanned<Instruction>]) -> String {
+    let mut out = String::from("digraph ast {\n    node [shape=box];\n");
+    let mut next_id = 0;
+    for statement in ast {
+        dot_node(&statement.node, &mut out, &mut next_id);
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Writes `node` and everything below it, returning the id it was given.
+fn dot_node(
+    node: &Instruction,
+    out: &mut String,
+    next_id: &mut usize,
+) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+
+    let (label, children): (String, Vec<(Option<&str>, _)>) = match node {
+        Instruction::StringLiteral(value) => {
+            (format!("StringLiteral {:?}", value), vec![])
+        }
+        Instruction::IntLiteral(value) => {
+            (format!("IntLiteral {}", value), vec![])
+        }
+        Instruction::FloatLiteral(value) => {
+            (format!("FloatLiteral {:?}", value), vec![])
+        }
+        Instruction::BooleanLiteral(value) => {
+            (format!("BooleanLiteral {}", value), vec![])
+        }
+        Instruction::FunctionCall { name, args } => {
+            (format!("FunctionCall {}", name), edges(None, args))
+        }
+        Instruction::Spread(value) => {
+            ("Spread".to_string(), vec![(None, &**value)])
+        }
+        Instruction::Variable { scope, name, value } => (
+            format!("Variable {} {}", scope, name),
+            vec![(None, &**value)],
+        ),
+        Instruction::VariableReference(name) => {
+            (format!("VariableReference {}", name), vec![])
+        }
+        Instruction::Array(values) => {
+            ("Array".to_string(), edges(None, values))
+        }
+        Instruction::Object(fields) => (
+            "Object".to_string(),
+            fields
+                .iter()
+                .map(|(key, value)| (Some(key.as_str()), value))
+                .collect(),
+        ),
+        Instruction::Assign { name, value } => {
+            (format!("Assign {}", name), vec![(None, &**value)])
+        }
+        Instruction::BinaryOp { op, lhs, rhs } => (
+            format!("BinaryOp {}", op),
+            vec![(Some("lhs"), &**lhs), (Some("rhs"), &**rhs)],
+        ),
+        Instruction::UnaryOp { op, operand } => {
+            (format!("UnaryOp {}", op), vec![(None, &**operand)])
+        }
+        Instruction::Index { target, index } => (
+            "Index".to_string(),
+            vec![(Some("target"), &**target), (Some("index"), &**index)],
+        ),
+        Instruction::Member { target, field } => {
+            (format!("Member {}", field), vec![(None, &**target)])
+        }
+        Instruction::Sequence(values) => {
+            ("Sequence".to_string(), edges(None, values))
+        }
+        Instruction::IfElse {
+            condition,
+            then_body,
+            else_body,
+        } => (
+            "IfElse".to_string(),
+            [(Some("condition"), &**condition)]
+                .into_iter()
+                .chain(edges(Some("then"), then_body))
+                .chain(edges(Some("else"), else_body))
+                .collect(),
+        ),
+        Instruction::WhileBlock { condition, body } => (
+            "WhileBlock".to_string(),
+            [(Some("condition"), &**condition)]
+                .into_iter()
+                .chain(edges(Some("body"), body))
+                .collect(),
+        ),
+        Instruction::FunctionDef { name, params, body } => (
+            format!("FunctionDef {}({})", name, params.join(", ")),
+            edges(None, body),
+        ),
+        Instruction::Return(value) => (
+            "Return".to_string(),
+            value.iter().map(|value| (None, &**value)).collect(),
+        ),
+        Instruction::Throw(value) => {
+            ("Throw".to_string(), vec![(None, &**value)])
+        }
+        Instruction::Break => ("Break".to_string(), vec![]),
+        Instruction::Continue => ("Continue".to_string(), vec![]),
+        Instruction::ForBlock {
+            init,
+            condition,
+            update,
+            body,
+        } => (
+            "ForBlock".to_string(),
+            [
+                (Some("init"), &**init),
+                (Some("condition"), &**condition),
+                (Some("update"), &**update),
+            ]
+            .into_iter()
+            .chain(edges(Some("body"), body))
+            .collect(),
+        ),
+        Instruction::TryCatch {
+            body,
+            param,
+            handler,
+        } => (
+            format!("TryCatch {}", param),
+            edges(Some("try"), body)
+                .into_iter()
+                .chain(edges(Some("catch"), handler))
+                .collect(),
+        ),
+    };
+
+    out.push_str(&format!("    n{} [label={:?}];\n", id, label));
+    for (edge, child) in children {
+        let child_id = dot_node(&child.node, out, next_id);
+        match edge {
+            Some(edge) => out.push_str(&format!(
+                "    n{} -> n{} [label={:?}];\n",
+                id, child_id, edge
+            )),
+            None => out.push_str(&format!("    n{} -> n{};\n", id, child_id)),
+        }
+    }
+
+    id
+}
+
+fn edges<'a>(
+    label: Option<&'a str>,
+    children: &'a [Spanned<Instruction>],
+) -> Vec<(Option<&'a str>, &'a Spanned<Instruction>)> {
+    children.iter().map(|child| (label, child)).collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DiagnosticKind {
+    Syntax,
+    Type,
+}
+
+/// An error found in a program, with the message and spans its rendered
+/// forms are built from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub span: Range<usize>,
+    pub message: String,
+    /// Shown under `span`: what was found there, or the custom message.
+    pub label: String,
+    /// The delimiter left unclosed, and where it was opened.
+    pub unclosed: Option<(char, Range<usize>)>,
+}
+
+impl From<Simple<char>> for Diagnostic {
+    fn from(e: Simple<char>) -> Self {
+        let message = if let SimpleReason::Custom(msg) = e.reason() {
+            msg.clone()
+        } else {
+            format!(
+                "{}{}, expected {}",
+                if e.found().is_some() {
+                    "Unexpected token"
+                } else {
+                    "Unexpected end of input"
+                },
+                if let Some(label) = e.label() {
+                    format!(" while parsing {}", label)
+                } else {
+                    String::new()
+                },
+                if e.expected().len() == 0 {
+                    "something else".to_string()
+                } else {
+                    e.expected()
+                        .map(|expected| match expected {
+                            Some(expected) => expected.to_string(),
+                            None => "end of input".to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                },
+            )
+        };
+
+        let label = match e.reason() {
+            SimpleReason::Custom(msg) => msg.clone(),
+            _ => format!(
+                "Unexpected {}",
+                e.found()
+                    .map(|c| format!("token {}", c))
+                    .unwrap_or_else(|| "end of input".to_string())
+            ),
+        };
+
+        let unclosed = match e.reason() {
+            SimpleReason::Unclosed { span, delimiter } => {
+                Some((*delimiter, span.clone()))
+            }
+            SimpleReason::Unexpected | SimpleReason::Custom(_) => None,
+        };
+
+        Diagnostic {
+            kind: DiagnosticKind::Syntax,
+            span: e.span(),
+            message,
+            label,
+            unclosed,
+        }
+    }
+}
+
+/// Parses a whole program, failing with every error found if there are any.
+pub fn parse(src: &str) -> Result<Vec<Spanned<Instruction>>, Vec<Diagnostic>> {
+    match parse_recovery(src) {
+        (Some(ast), errs) if errs.is_empty() => Ok(ast),
+        (_, errs) => Err(errs),
+    }
+}
+
+/// Parses a whole program, returning whatever could be recovered from it
+/// alongside any errors, for tools that still want to show the partial AST.
+pub fn parse_recovery(
+    src: &str,
+) -> (Option<Vec<Spanned<Instruction>>>, Vec<Diagnostic>) {
+    let (ast, errs) = parser().parse_recovery(src);
+    (ast, errs.into_iter().map(Diagnostic::from).collect())
+}
+
+#[cfg(feature = "report")]
+/// Writes a diagnostic as a single `file:line:col: error: message` line,
+/// for editors and grep rather than people reading a terminal.
+pub fn write_compact(
+    e: &Diagnostic,
+    file: &str,
+    src: &str,
+    mut w: impl std::io::Write,
+) -> std::io::Result<()> {
+    // Spans count chars, not bytes, and both line and column are 1-based.
+    let (line, col) =
+        src.chars()
+            .take(e.span.start)
+            .fold((1, 1), |(line, col), c| {
+                if c == '\n' {
+                    (line + 1, 1)
+                } else {
+                    (line, col + 1)
+                }
+            });
+
+    writeln!(w, "{}:{}:{}: error: {}", file, line, col, e.message)
+}
+
+#[cfg(feature = "report")]
+/// Renders a diagnostic as an ariadne report into `w`, with ANSI colours
+/// only if `color` is set.
+///
+/// `src` is the text that was parsed; its CRLF line endings needn't be
+/// normalised first.
+pub fn write_report(
+    e: &Diagnostic,
+    src: &str,
+    w: impl std::io::Write,
+    color: bool,
+) -> std::io::Result<()> {
+    let red = color.then_some(Color::Red);
+    let yellow = color.then_some(Color::Yellow);
+    // `Config::with_color` doesn't reach the colours given to labels, so
+    // those are only set when colour is on.
+    let label = |span, color: Option<Color>| {
+        let label = Label::new(span);
+        match color {
+            Some(color) => label.with_color(color),
+            None => label,
+        }
+    };
+
+    let report = Report::build(ReportKind::Error, (), e.span.start)
+        .with_config(Config::default().with_color(color))
+        .with_code(match e.kind {
+            DiagnosticKind::Syntax => 3,
+            DiagnosticKind::Type => 4,
+        })
+        .with_message(&e.message)
+        .with_label(label(e.span.clone(), red).with_message(&e.label));
+
+    let report = match &e.unclosed {
+        Some((delimiter, span)) => {
+            report.with_label(label(span.clone(), yellow).with_message(
+                format!("Unclosed delimiter {}", delimiter.fg(yellow)),
+            ))
+        }
+        None => report,
+    };
+
+    report.finish().write(Source::from(src), w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Spans are ignored by `==`, so expected trees can leave them empty.
+    fn node(node: Instruction) -> Spanned<Instruction> {
+        Spanned::new(node, 0..0)
+    }
+
+    fn boxed(value: Instruction) -> Box<Spanned<Instruction>> {
+        Box::new(node(value))
+    }
+
+    fn reference(name: &str) -> Instruction {
+        Instruction::VariableReference(name.to_string())
+    }
+
+    fn errors(src: &str) -> Vec<String> {
+        parse(src)
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.message)
+            .collect()
+    }
+
+    #[test]
+    fn async_and_await() {
+        assert_eq!(
+            parse("async function f() { await g(await x); }"),
+            Ok(vec![node(Instruction::FunctionDef {
+                name: "f".to_string(),
+                params: vec![],
+                body: vec![node(Instruction::FunctionCall {
+                    name: "g".to_string(),
+                    args: vec![node(Instruction::VariableReference(
+                        "x".to_string()
+                    ))],
+                })],
+            })])
+        );
+    }
+
+    #[cfg(feature = "report")]
+    #[test]
+    fn crlf_positions() {
+        // A `\r` is counted like any other character, as ariadne does when
+        // it works out the line and column of a span.
+        let src = "f(1);\r\nf(;";
+        let errs = parse(src).unwrap_err();
+        assert_eq!(errs[0].span, 9..10);
+
+        let mut out = Vec::new();
+        write_report(&errs[0], src, &mut out, false).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("<unknown>:2:3") && !out.contains('\r'));
+    }
+
+    #[cfg(feature = "report")]
+    #[test]
+    fn report_into_writer() {
+        let src = "f(1;";
+        let errs = parse(src).unwrap_err();
+
+        let mut out = Vec::new();
+        write_report(&errs[0], src, &mut out, false).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("[03] Error: Unexpected token, expected"));
+        assert!(out.contains("<unknown>:1:4") && out.contains("f(1;"));
+    }
+
+    #[cfg(feature = "report")]
+    #[test]
+    fn report_colours() {
+        let src = "f(1;";
+        let errs = parse(src).unwrap_err();
+        let render = |color| {
+            let mut out = Vec::new();
+            write_report(&errs[0], src, &mut out, color).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert!(!render(false).contains('\x1b'));
+        assert!(render(true).contains('\x1b'));
+    }
+
+    #[test]
+    fn empty_program() {
+        for src in ["", "  \n "] {
+            assert_eq!(parse(src), Ok(vec![]));
+        }
+    }
+
+    #[test]
+    fn variable_scopes() {
+        for (src, scope) in [
+            ("let x = y", VariableScope::Let),
+            ("const x = y", VariableScope::Const),
+            ("var x = y", VariableScope::Var),
+        ] {
+            assert_eq!(
+                parse(src),
+                Ok(vec![node(Instruction::Variable {
+                    scope,
+                    name: "x".to_string(),
+                    value: boxed(Instruction::VariableReference(
+                        "y".to_string()
+                    )),
+                })])
+            );
+        }
+    }
+
+    #[test]
+    fn chained_assignment() {
+        assert_eq!(
+            parse("a = b = 5"),
+            Ok(vec![node(Instruction::Assign {
+                name: "a".to_string(),
+                value: boxed(Instruction::Assign {
+                    name: "b".to_string(),
+                    value: boxed(Instruction::IntLiteral(5)),
+                }),
+            })])
+        );
+    }
+
+    #[test]
+    fn binary_operators() {
+        let int = |value| boxed(Instruction::IntLiteral(value));
+        assert_eq!(
+            parse("let x = 1 + 2;"),
+            Ok(vec![node(Instruction::Variable {
+                scope: VariableScope::Let,
+                name: "x".to_string(),
+                value: boxed(Instruction::BinaryOp {
+                    op: BinOp::Add,
+                    lhs: int(1),
+                    rhs: int(2),
+                }),
+            })])
+        );
+        // Products bind tighter than sums, and both are left-associative.
+        assert_eq!(
+            parse("1 - 2 * 3 % 4"),
+            Ok(vec![node(Instruction::BinaryOp {
+                op: BinOp::Sub,
+                lhs: int(1),
+                rhs: boxed(Instruction::BinaryOp {
+                    op: BinOp::Mod,
+                    lhs: boxed(Instruction::BinaryOp {
+                        op: BinOp::Mul,
+                        lhs: int(2),
+                        rhs: int(3),
+                    }),
+                    rhs: int(4),
+                }),
+            })])
+        );
+    }
+
+    #[test]
+    fn ast_diff_single_change() {
+        let old = parse("let a = 1;\nlet b = 2;\nf(a, b);").unwrap();
+        let new = parse("let a = 1;\nlet b = 3;\nf(a, b);").unwrap();
+
+        assert_eq!(ast_diff(&old, &new), [Change::Modified { old: 1, new: 1 }]);
+        assert_eq!(ast_diff(&old, &old), []);
+    }
+
+    #[test]
+    fn if_else() {
+        let log = |value: &str| {
+            node(Instruction::FunctionCall {
+                name: "console.log".to_string(),
+                args: vec![node(Instruction::StringLiteral(value.to_string()))],
+            })
+        };
+        let x = || boxed(Instruction::VariableReference("x".to_string()));
+        assert_eq!(
+            parse(r#"if (x) { console.log("a") } else { console.log("b") }"#),
+            Ok(vec![node(Instruction::IfElse {
+                condition: x(),
+                then_body: vec![log("a")],
+                else_body: vec![log("b")],
+            })])
+        );
+        assert_eq!(
+            parse(r#"if (x) {} else if (x) { console.log("b") }"#),
+            Ok(vec![node(Instruction::IfElse {
+                condition: x(),
+                then_body: vec![],
+                else_body: vec![node(Instruction::IfElse {
+                    condition: x(),
+                    then_body: vec![log("b")],
+                    else_body: vec![],
+                })],
+            })])
+        );
+    }
+
+    #[test]
+    fn shebang() {
+        assert_eq!(
+            parse("#!/usr/bin/env trippy\nx"),
+            Ok(vec![node(Instruction::VariableReference("x".to_string()))])
+        );
+        assert!(parse("x\n#!/usr/bin/env trippy").is_err());
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(
+            parse("while (i < 10) {}"),
+            Ok(vec![node(Instruction::WhileBlock {
+                condition: boxed(Instruction::BinaryOp {
+                    op: BinOp::Lt,
+                    lhs: boxed(Instruction::VariableReference("i".to_string())),
+                    rhs: boxed(Instruction::IntLiteral(10)),
+                }),
+                body: vec![],
+            })])
+        );
+        assert_eq!(
+            errors("a < b < c"),
+            ["Comparison operators cannot be chained"]
+        );
+        // `==` is a comparison, not an assignment of `= b`.
+        assert!(matches!(
+            parse("a == b").unwrap()[0].node,
+            Instruction::BinaryOp { op: BinOp::Eq, .. }
+        ));
+    }
+
+    #[test]
+    fn unary_operators() {
+        assert_eq!(
+            parse("-5; !0"),
+            Ok(vec![
+                node(Instruction::UnaryOp {
+                    op: UnOp::Neg,
+                    operand: boxed(Instruction::IntLiteral(5)),
+                }),
+                node(Instruction::UnaryOp {
+                    op: UnOp::Not,
+                    operand: boxed(Instruction::IntLiteral(0)),
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn comma_operator() {
+        assert_eq!(
+            parse("(a, b)"),
+            Ok(vec![node(Instruction::Sequence(vec![
+                node(reference("a")),
+                node(reference("b")),
+            ]))])
+        );
+        // Argument commas stay argument separators.
+        assert_eq!(
+            parse("f(a, b)"),
+            Ok(vec![node(Instruction::FunctionCall {
+                name: "f".to_string(),
+                args: vec![node(reference("a")), node(reference("b"))],
+            })])
+        );
+    }
+
+    #[test]
+    fn int_and_float_literals() {
+        assert_eq!(parse("2"), Ok(vec![node(Instruction::IntLiteral(2))]));
+        assert_eq!(
+            parse("2.0"),
+            Ok(vec![node(Instruction::FloatLiteral(2.0))])
+        );
+    }
+
+    #[test]
+    fn trailing_line_comment() {
+        assert_eq!(
+            parse("let x = 1; // c"),
+            Ok(vec![node(Instruction::Variable {
+                scope: VariableScope::Let,
+                name: "x".to_string(),
+                value: boxed(Instruction::IntLiteral(1)),
+            })])
+        );
+    }
+
+    #[test]
+    fn nested_block_comment() {
+        assert!(errors("/* a /* b */ */ x")
+            .contains(&"Nested block comments are not supported".to_string()));
+    }
+
+    #[test]
+    fn comments_are_not_expected() {
+        for src in ["let = ;", "let x = ;", "1."] {
+            for message in errors(src) {
+                assert!(!message.contains('/'), "{}: {}", src, message);
+            }
+        }
+    }
+
+    #[test]
+    fn chained_indexing() {
+        let int = |value| boxed(Instruction::IntLiteral(value));
+        assert_eq!(
+            parse("a[0][1]"),
+            Ok(vec![node(Instruction::Index {
+                target: boxed(Instruction::Index {
+                    target: boxed(Instruction::VariableReference(
+                        "a".to_string()
+                    )),
+                    index: int(0),
+                }),
+                index: int(1),
+            })])
+        );
+    }
+
+    #[test]
+    fn member_access() {
+        assert_eq!(
+            parse("foo.bar"),
+            Ok(vec![node(Instruction::Member {
+                target: boxed(Instruction::VariableReference(
+                    "foo".to_string()
+                )),
+                field: "bar".to_string(),
+            })])
+        );
+    }
+
+    #[test]
+    fn spaced_dotted_call() {
+        for src in ["console . log(1)", "console.\n  log(1)"] {
+            assert_eq!(
+                parse(src),
+                Ok(vec![node(Instruction::FunctionCall {
+                    name: "console.log".to_string(),
+                    args: vec![node(Instruction::IntLiteral(1))],
+                })])
+            );
+        }
+    }
+
+    #[test]
+    fn only_names_are_called() {
+        for src in ["let v = a[0](1)", "a(1)(2)"] {
+            assert_eq!(errors(src), ["Only names can be called"], "{}", src);
+        }
+    }
+
+    #[test]
+    fn literal_statements() {
+        assert_eq!(
+            parse("[1,2];"),
+            Ok(vec![node(Instruction::Array(vec![
+                node(Instruction::IntLiteral(1)),
+                node(Instruction::IntLiteral(2)),
+            ]))])
+        );
+        assert_eq!(
+            parse("({a:1});"),
+            Ok(vec![node(Instruction::Object(
+                [("a".to_string(), node(Instruction::IntLiteral(1)))].into()
+            ))])
+        );
+        // A leading `{` is a block, not an object.
+        assert!(parse("{a:1};").is_err());
+    }
+
+    #[test]
+    fn display() {
+        let display = |src| parse(src).unwrap()[0].to_string();
+        assert_eq!(display("const x = [1, 2.0]"), "const x = [1, 2.0]");
+        assert_eq!(
+            display(r#"({ b: "x", "a b": y })"#),
+            r#"{ "a b": y, b: "x" }"#
+        );
+        assert_eq!(display("({})"), "{}");
+        assert_eq!(display("while (x) { f(); }"), "while (x) { f(); }");
+    }
+
+    #[test]
+    fn function_definition() {
+        assert_eq!(
+            parse("function add(a, b) { a + b }\nadd(2, 3);"),
+            Ok(vec![
+                node(Instruction::FunctionDef {
+                    name: "add".to_string(),
+                    params: vec!["a".to_string(), "b".to_string()],
+                    body: vec![node(Instruction::BinaryOp {
+                        op: BinOp::Add,
+                        lhs: boxed(reference("a")),
+                        rhs: boxed(reference("b")),
+                    })],
+                }),
+                node(Instruction::FunctionCall {
+                    name: "add".to_string(),
+                    args: vec![
+                        node(Instruction::IntLiteral(2)),
+                        node(Instruction::IntLiteral(3)),
+                    ],
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn spread_arguments() {
+        assert_eq!(
+            parse("f(...arr, x);"),
+            Ok(vec![node(Instruction::FunctionCall {
+                name: "f".to_string(),
+                args: vec![
+                    node(Instruction::Spread(boxed(reference("arr")))),
+                    node(reference("x")),
+                ],
+            })])
+        );
+        assert_eq!(
+            parse("f(... g(1));").map(|p| p[0].to_string()),
+            Ok("f(...g(1))".to_string())
+        );
+    }
+
+    #[test]
+    fn return_statements() {
+        assert_eq!(
+            parse("function f() { return; }\nreturn 1 + 2;"),
+            Ok(vec![
+                node(Instruction::FunctionDef {
+                    name: "f".to_string(),
+                    params: vec![],
+                    body: vec![node(Instruction::Return(None))],
+                }),
+                node(Instruction::Return(Some(boxed(Instruction::BinaryOp {
+                    op: BinOp::Add,
+                    lhs: boxed(Instruction::IntLiteral(1)),
+                    rhs: boxed(Instruction::IntLiteral(2)),
+                })))),
+            ])
+        );
+    }
+
+    #[test]
+    fn for_loop() {
+        let program = parser()
+            .parse("for (let i = 0; i < 3; i = i + 1) { console.log(i); }")
+            .unwrap();
+        assert_eq!(
+            program,
+            vec![node(Instruction::ForBlock {
+                init: boxed(Instruction::Variable {
+                    scope: VariableScope::Let,
+                    name: "i".to_string(),
+                    value: boxed(Instruction::IntLiteral(0)),
+                }),
+                condition: boxed(Instruction::BinaryOp {
+                    op: BinOp::Lt,
+                    lhs: boxed(reference("i")),
+                    rhs: boxed(Instruction::IntLiteral(3)),
+                }),
+                update: boxed(Instruction::Assign {
+                    name: "i".to_string(),
+                    value: boxed(Instruction::BinaryOp {
+                        op: BinOp::Add,
+                        lhs: boxed(reference("i")),
+                        rhs: boxed(Instruction::IntLiteral(1)),
+                    }),
+                }),
+                body: vec![node(Instruction::FunctionCall {
+                    name: "console.log".to_string(),
+                    args: vec![node(reference("i"))],
+                })],
+            })]
+        );
+        assert_eq!(
+            program[0].to_string(),
+            "for (let i = 0; i < 3; i = i + 1) { console.log(i); }"
+        );
+    }
+
+    #[test]
+    fn break_and_continue() {
+        assert_eq!(
+            parse("while (1) { if (i == 3) { break; } continue; }"),
+            Ok(vec![node(Instruction::WhileBlock {
+                condition: boxed(Instruction::IntLiteral(1)),
+                body: vec![
+                    node(Instruction::IfElse {
+                        condition: boxed(Instruction::BinaryOp {
+                            op: BinOp::Eq,
+                            lhs: boxed(Instruction::VariableReference(
+                                "i".to_string()
+                            )),
+                            rhs: boxed(Instruction::IntLiteral(3)),
+                        }),
+                        then_body: vec![node(Instruction::Break)],
+                        else_body: vec![],
+                    }),
+                    node(Instruction::Continue),
+                ],
+            })])
+        );
+    }
+
+    #[test]
+    fn unclosed_block() {
+        let errs = parser().parse("if (x) { f(1);").unwrap_err();
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].label(), Some("block"));
+        assert!(errs[0].expected().any(|c| c == &Some('}')));
+        assert!(errs[0].to_string().contains("expected"));
+    }
+
+    #[test]
+    fn invalid_assignment_target() {
+        assert_eq!(errors("a[0] = 1"), ["Invalid assignment target"]);
+    }
+
+    #[test]
+    fn string_quotes() {
+        for (src, value) in [
+            (r#""it's""#, "it's"),
+            (r#"'say "hi"'"#, r#"say "hi""#),
+            (r#""\x41""#, "A"),
+            (r#""a\tb\u{1F600}""#, "a\tb\u{1F600}"),
+        ] {
+            assert_eq!(
+                parse(src),
+                Ok(vec![node(Instruction::StringLiteral(value.to_string()))])
+            );
+        }
+        assert!(parse(r#""abc'"#).is_err());
+        assert_eq!(errors(r#""\x4""#), ["Expected two hex digits after \\x"]);
+    }
+
+    #[test]
+    fn missing_brace_keeps_earlier_statements() {
+        let (ast, errs) =
+            parse_recovery("let x = 1;\nif (x) { console.log(x);\nlet y = 2;");
+        assert_eq!(errs.len(), 1);
+        assert_eq!(ast.unwrap()[0], parse("let x = 1;").unwrap()[0]);
+    }
+
+    #[test]
+    fn missing_paren_is_recovered() {
+        for src in [
+            "f(1;",
+            "if (x { 1 }",
+            "while (x { 1 }",
+            "for (let i = 0; i < 3; i = i + 1 { 1 }",
+            "function f(a, b { 1 }",
+            "try {} catch (e {}",
+        ] {
+            let (ast, errs) = parse_recovery(src);
+            assert!(ast.is_some_and(|ast| ast.len() == 1), "{}", src);
+            assert_eq!(errs[0].unclosed.as_ref().unwrap().0, '(', "{}", src);
+        }
+    }
+
+    #[test]
+    fn trailing_commas() {
+        let int = |value| node(Instruction::IntLiteral(value));
+        assert_eq!(
+            parse("f(1,)"),
+            Ok(vec![node(Instruction::FunctionCall {
+                name: "f".to_string(),
+                args: vec![int(1)],
+            })])
+        );
+        assert_eq!(
+            parse("[1,]"),
+            Ok(vec![node(Instruction::Array(vec![int(1)]))])
+        );
+        assert_eq!(
+            parse("({a:1,})"),
+            Ok(vec![node(Instruction::Object(
+                [("a".to_string(), int(1))].into_iter().collect()
+            ))])
+        );
+        assert_eq!(
+            parse("function f(a,) {}"),
+            Ok(vec![node(Instruction::FunctionDef {
+                name: "f".to_string(),
+                params: vec!["a".to_string()],
+                body: vec![],
+            })])
+        );
+        for src in ["f(,)", "[,]", "(1,)"] {
+            assert!(parse(src).is_err(), "{}", src);
+        }
+    }
+
+    #[test]
+    fn mixed_top_level_statements() {
+        let src = "let i = 0\n\
+                   while (i < 3) { i = i + 1; }\n\
+                   console.log(i);\n\
+                   if (i) { console.log(\"done\") }\n\
+                   i + 1";
+        assert_eq!(parse(src).map(|program| program.len()), Ok(5));
+    }
+
+    #[cfg(feature = "report")]
+    #[test]
+    fn compact_format() {
+        let compact = |src| {
+            let mut out = Vec::new();
+            let errs = parse(src).unwrap_err();
+            write_compact(&errs[0], "a.ts", src, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            compact("let x = 1;\n  let = 2;"),
+            "a.ts:2:7: error: Unexpected token, expected something else\n"
+        );
+        // The `\r` of a CRLF ending belongs to the line before.
+        assert_eq!(
+            compact("let x = 1;\r\n  let = 2;"),
+            "a.ts:2:7: error: Unexpected token, expected something else\n"
+        );
+    }
+
+    #[test]
+    fn parse_errors_are_returned() {
+        let errs = parse("let = ;").unwrap_err();
+        assert!(!errs.is_empty());
+        assert_eq!(errs[0].span, 4..5);
+    }
+
+    #[test]
+    fn parse_recovery_keeps_the_ast() {
+        let (ast, errs) = parse_recovery("let x = 1;\nlet y = (2;");
+        assert_eq!(errs.len(), 1);
+        assert_eq!(ast.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn radix_literals() {
+        for (src, value) in [("0xFF", 255), ("0b1010", 10), ("0X10", 16)] {
+            assert_eq!(
+                parse(src),
+                Ok(vec![node(Instruction::IntLiteral(value))])
+            );
+        }
+        assert_eq!(errors("0x"), ["Expected digits after 0x"]);
+        assert_eq!(
+            errors("x = 0b102"),
+            ["Invalid character `2` in numeric literal"]
+        );
+    }
+
+    #[test]
+    fn exponents() {
+        for (src, value) in
+            [("1e3", 1000.0), ("2.5E-3", 0.0025), ("1.e3", 1000.0)]
+        {
+            assert_eq!(
+                parse(src),
+                Ok(vec![node(Instruction::FloatLiteral(value))])
+            );
+        }
+        assert_eq!(errors("1e"), ["Expected digits in exponent"]);
+        assert_eq!(errors("1.e"), ["Expected digits in exponent"]);
+        assert_eq!(errors("1e999"), ["Float literal is too large"]);
+    }
+
+    #[test]
+    fn try_catch_statement() {
+        assert_eq!(
+            parse("try { f(); } catch (e) { g(e); }"),
+            Ok(vec![node(Instruction::TryCatch {
+                body: parse("f()").unwrap(),
+                param: "e".to_string(),
+                handler: parse("g(e)").unwrap(),
+            })])
+        );
+        assert!(parse("try { f(); }").is_err());
+    }
+
+    #[test]
+    fn numeric_separators() {
+        for (src, value) in [("1_000", 1000), ("0X1_0", 16), ("007", 7)] {
+            assert_eq!(
+                parse(src),
+                Ok(vec![node(Instruction::IntLiteral(value))])
+            );
+        }
+        assert_eq!(
+            parse("1_0.2_5e1_0"),
+            Ok(vec![node(Instruction::FloatLiteral(10.25e10))])
+        );
+        for src in ["1__0", "1_", "0x_1", "1._5"] {
+            assert_eq!(
+                errors(src),
+                ["Numeric separators must be between two digits"],
+                "{}",
+                src
+            );
+        }
+    }
+
+    #[test]
+    fn throw_statement() {
+        assert_eq!(
+            parse(r#"throw "oops";"#),
+            Ok(vec![node(Instruction::Throw(Box::new(node(
+                Instruction::StringLiteral("oops".to_string())
+            ))))])
+        );
+        assert!(parse("throw;").is_err());
+    }
+
+    #[test]
+    fn equality_ignores_spans() {
+        assert_eq!(parse("f(1,2)").unwrap(), parse("  f( 1, 2 )").unwrap());
+        assert_ne!(parse("f(1, 2)").unwrap(), parse("f(2, 1)").unwrap());
+    }
+
+    #[test]
+    fn nested_spans() {
+        let ast = parse("x = 1 + 2 * 3;").unwrap();
+        let Instruction::Assign { value, .. } = &ast[0].node else {
+            panic!("{:?}", ast);
+        };
+        let Instruction::BinaryOp { lhs, rhs, .. } = &value.node else {
+            panic!("{:?}", value);
+        };
+        assert_eq!(ast[0].span, 0..13);
+        assert_eq!((value.span.clone(), lhs.span.clone()), (4..13, 4..5));
+        assert_eq!(rhs.span, 8..13);
+
+        let ast = parse("a[0].b").unwrap();
+        let Instruction::Member { target, .. } = &ast[0].node else {
+            panic!("{:?}", ast);
+        };
+        assert_eq!((ast[0].span.clone(), target.span.clone()), (0..6, 0..4));
+
+        let ast = parse("- !x").unwrap();
+        let Instruction::UnaryOp { operand, .. } = &ast[0].node else {
+            panic!("{:?}", ast);
+        };
+        assert_eq!((ast[0].span.clone(), operand.span.clone()), (0..4, 2..4));
+
+        // Statements end with their value, not a comment or `;` after it.
+        let ast = parse("let x = 1   // trailing\nif (x) {}").unwrap();
+        assert_eq!((ast[0].span.clone(), ast[1].span.clone()), (0..9, 24..33));
+        let ast = parse("let y = 1 /* c */;").unwrap();
+        assert_eq!(ast[0].span, 0..9);
+        for (src, span) in [("return 1 // c", 0..8), ("throw 1 /* c */", 0..7)]
+        {
+            assert_eq!(parse(src).unwrap()[0].span, span, "{}", src);
+        }
+        let ast = parse("while (x) { f(); }  ").unwrap();
+        assert_eq!(ast[0].span, 0..18);
+    }
+
+    #[test]
+    fn dot_node_per_instruction() {
+        let dot = to_dot(&parse("let x = 1 + 2; f(x);").unwrap());
+        let nodes = dot
+            .lines()
+            .filter(|line| line.contains("[label=") && !line.contains("->"))
+            .count();
+        // Variable, BinaryOp, two IntLiterals, FunctionCall and
+        // VariableReference.
+        assert_eq!(nodes, 6);
+        assert_eq!(dot.matches(" -> ").count(), 4);
+    }
+
+    #[test]
+    fn boolean_literals() {
+        assert_eq!(
+            parse("true; false; trueish").unwrap(),
+            vec![
+                node(Instruction::BooleanLiteral(true)),
+                node(Instruction::BooleanLiteral(false)),
+                node(reference("trueish")),
+            ]
+        );
+        assert_eq!(
+            parse("while (true) {}"),
+            Ok(vec![node(Instruction::WhileBlock {
+                condition: boxed(Instruction::BooleanLiteral(true)),
+                body: vec![],
+            })])
+        );
+    }
+
+    #[test]
+    fn reserved_keywords() {
+        assert_eq!(errors("let while = 1;"), ["`while` is a reserved keyword"]);
+        assert_eq!(errors("switch (x) {}"), ["Unexpected keyword"]);
+        assert!(errors("if {}")[0].ends_with("expected ("));
+        assert!(parse("true; await f(); async function f() {}").is_ok());
+        assert!(parse("while (false) {} console.log.while(1);").is_ok());
+    }
 }
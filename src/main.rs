@@ -1,80 +1,89 @@
-use ariadne::{Color, Fmt, Label, Report, ReportKind, Source};
-use chumsky::Parser;
-use trippy::parser;
+//! Exits with `0` when the input parses and type checks, `1` if it fails to
+//! parse, `2` if it fails to type check, and `64` if the arguments are wrong
+//! or the file can't be read.
+//!
+//! The AST, or as much of it as could be recovered, is printed with `Debug`,
+//! or as a Graphviz graph with `--emit=dot`. Errors are written to stderr as
+//! ariadne reports unless `--pretty-errors=false` is given, in which case
+//! each is printed as one `file:line:col` line.
+
+use std::io::IsTerminal;
+
+use trippy::{
+    parse_recovery, to_dot, typecheck::typecheck, write_compact, write_report,
+};
+
+/// `EX_USAGE` from sysexits.h.
+const USAGE: i32 = 64;
+
+fn usage(message: impl std::fmt::Display) -> ! {
+    eprintln!("trippy: {}", message);
+    eprintln!(
+        "usage: trippy [--pretty-errors=true|false] [--emit=ast|dot] <file>"
+    );
+    std::process::exit(USAGE)
+}
 
 fn main() {
-    let src = std::fs::read_to_string(
-        std::env::args().nth(1).expect("Expected file argument"),
-    )
-    .expect("Failed to read file");
+    let mut file = None;
+    let mut pretty = true;
+    let mut dot = false;
+    for arg in std::env::args().skip(1) {
+        if let Some(value) = arg.strip_prefix("--pretty-errors=") {
+            pretty = value.parse().unwrap_or_else(|_| {
+                usage("expected --pretty-errors=true or --pretty-errors=false")
+            });
+        } else if let Some(value) = arg.strip_prefix("--emit=") {
+            dot = match value {
+                "ast" => false,
+                "dot" => true,
+                _ => usage("expected --emit=ast or --emit=dot"),
+            };
+        } else {
+            file = Some(arg);
+        }
+    }
+    let file = file.unwrap_or_else(|| usage("expected a file argument"));
 
-    let (json, errs) = parser().parse_recovery(src.trim());
-    println!("{:#?}", json);
-    errs.into_iter().for_each(|e| {
-        let msg = if let chumsky::error::SimpleReason::Custom(msg) = e.reason()
-        {
-            msg.clone()
+    let src = std::fs::read_to_string(&file)
+        .unwrap_or_else(|e| usage(format_args!("can't read {}: {}", file, e)));
+
+    // The AST is printed even if there were errors, since recovery may still
+    // have got most of it.
+    let (ast, errs) = parse_recovery(&src);
+    if let Some(ast) = &ast {
+        if dot {
+            print!("{}", to_dot(ast));
         } else {
-            format!(
-                "{}{}, expected {}",
-                if e.found().is_some() {
-                    "Unexpected token"
-                } else {
-                    "Unexpected end of input"
-                },
-                if let Some(label) = e.label() {
-                    format!(" while parsing {}", label)
-                } else {
-                    String::new()
-                },
-                if e.expected().len() == 0 {
-                    "something else".to_string()
-                } else {
-                    e.expected()
-                        .map(|expected| match expected {
-                            Some(expected) => expected.to_string(),
-                            None => "end of input".to_string(),
-                        })
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                },
-            )
-        };
+            println!("{:#?}", ast);
+        }
+    }
 
-        let report = Report::build(ReportKind::Error, (), e.span().start)
-            .with_code(3)
-            .with_message(msg)
-            .with_label(
-                Label::new(e.span())
-                    .with_message(match e.reason() {
-                        chumsky::error::SimpleReason::Custom(msg) => {
-                            msg.clone()
-                        }
-                        _ => format!(
-                            "Unexpected {}",
-                            e.found()
-                                .map(|c| format!("token {}", c.fg(Color::Red)))
-                                .unwrap_or_else(|| "end of input".to_string())
-                        ),
-                    })
-                    .with_color(Color::Red),
-            );
+    let (errs, code) = match ast {
+        Some(ast) if errs.is_empty() => (
+            typecheck(&ast)
+                .err()
+                .unwrap_or_default()
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            2,
+        ),
+        _ => (errs, 1),
+    };
 
-        let report = match e.reason() {
-            chumsky::error::SimpleReason::Unclosed { span, delimiter } => {
-                report.with_label(
-                    Label::new(span.clone())
-                        .with_message(format!(
-                            "Unclosed delimiter {}",
-                            delimiter.fg(Color::Yellow)
-                        ))
-                        .with_color(Color::Yellow),
-                )
+    if !errs.is_empty() {
+        // Diagnostics go to stderr, so that stdout can be piped into `dot`
+        // or elsewhere. Leave escape sequences out of redirected output such
+        // as log files.
+        let color = std::io::stderr().is_terminal();
+        errs.iter().for_each(|e| {
+            if pretty {
+                write_report(e, &src, std::io::stderr(), color).unwrap()
+            } else {
+                write_compact(e, &file, &src, std::io::stderr()).unwrap()
             }
-            chumsky::error::SimpleReason::Unexpected => report,
-            chumsky::error::SimpleReason::Custom(_) => report,
-        };
-
-        report.finish().print(Source::from(&src)).unwrap();
-    });
+        });
+        std::process::exit(code);
+    }
 }
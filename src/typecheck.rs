@@ -0,0 +1,343 @@
+//! A best-effort type check over a parsed program.
+//!
+//! Each expression is given one of a handful of types, and operators applied
+//! to types they can't handle are reported. Anything that can't be known
+//! statically, such as call results or function parameters, is `Unknown` and
+//! accepted everywhere.
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt, ops::Range};
+
+use crate::{BinOp, Diagnostic, DiagnosticKind, Instruction, Spanned, UnOp};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Type {
+    Int,
+    Float,
+    String,
+    Bool,
+    Object,
+    Array,
+    Unknown,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Type::Int => "int",
+            Type::Float => "float",
+            Type::String => "string",
+            Type::Bool => "bool",
+            Type::Object => "object",
+            Type::Array => "array",
+            Type::Unknown => "unknown",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeError {
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+/// Lets type errors be rendered by `write_report` and `write_compact`.
+impl From<TypeError> for Diagnostic {
+    fn from(e: TypeError) -> Self {
+        Diagnostic {
+            kind: DiagnosticKind::Type,
+            span: e.span,
+            label: e.message.clone(),
+            message: e.message,
+            unclosed: None,
+        }
+    }
+}
+
+pub fn typecheck(ast: &[Spanned<Instruction>]) -> Result<(), Vec<TypeError>> {
+    let mut checker = Checker { errors: Vec::new() };
+    checker.statements(ast, &mut BTreeMap::new());
+
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(checker.errors)
+    }
+}
+
+type Scope = BTreeMap<String, Type>;
+
+struct Checker {
+    errors: Vec<TypeError>,
+}
+
+impl Checker {
+    fn statements(&mut self, body: &[Spanned<Instruction>], scope: &mut Scope) {
+        for statement in body {
+            self.check(statement, scope);
+        }
+    }
+
+    /// Checks `body` in a scope of its own.
+    fn block(&mut self, body: &[Spanned<Instruction>], scope: &mut Scope) {
+        let mut inner = scope.clone();
+        self.statements(body, &mut inner);
+        merge(scope, &inner);
+    }
+
+    fn check(
+        &mut self,
+        node: &Spanned<Instruction>,
+        scope: &mut Scope,
+    ) -> Type {
+        match &node.node {
+            Instruction::StringLiteral(_) => Type::String,
+            Instruction::IntLiteral(_) => Type::Int,
+            Instruction::FloatLiteral(_) => Type::Float,
+            Instruction::BooleanLiteral(_) => Type::Bool,
+            Instruction::FunctionCall { args, .. } => {
+                for arg in args {
+                    self.check(arg, scope);
+                }
+                Type::Unknown
+            }
+            Instruction::Spread(value) => {
+                self.check(value, scope);
+                Type::Unknown
+            }
+            Instruction::Variable { name, value, .. }
+            | Instruction::Assign { name, value } => {
+                let ty = self.check(value, scope);
+                scope.insert(name.clone(), ty);
+                ty
+            }
+            Instruction::VariableReference(name) => {
+                scope.get(name).copied().unwrap_or(Type::Unknown)
+            }
+            Instruction::Array(values) => {
+                for value in values {
+                    self.check(value, scope);
+                }
+                Type::Array
+            }
+            Instruction::Object(fields) => {
+                for value in fields.values() {
+                    self.check(value, scope);
+                }
+                Type::Object
+            }
+            Instruction::BinaryOp { op, lhs, rhs } => {
+                let lhs = self.check(lhs, scope);
+                let rhs = self.check(rhs, scope);
+                self.binary_op(op, lhs, rhs, &node.span)
+            }
+            Instruction::UnaryOp { op, operand } => {
+                let operand = self.check(operand, scope);
+                match (op, operand) {
+                    (UnOp::Not, _) => Type::Bool,
+                    (UnOp::Neg, Type::Int | Type::Float | Type::Unknown) => {
+                        operand
+                    }
+                    (UnOp::Neg, _) => {
+                        self.error(
+                            &node.span,
+                            format!("Cannot negate {}", operand),
+                        );
+                        Type::Unknown
+                    }
+                }
+            }
+            Instruction::Index { target, index } => {
+                self.check(target, scope);
+                self.check(index, scope);
+                Type::Unknown
+            }
+            Instruction::Member { target, .. } => {
+                self.check(target, scope);
+                Type::Unknown
+            }
+            Instruction::Sequence(values) => values
+                .iter()
+                .map(|value| self.check(value, scope))
+                .last()
+                .unwrap_or(Type::Unknown),
+            Instruction::IfElse {
+                condition,
+                then_body,
+                else_body,
+            } => {
+                self.check(condition, scope);
+                self.block(then_body, scope);
+                self.block(else_body, scope);
+                Type::Unknown
+            }
+            Instruction::WhileBlock { condition, body } => {
+                self.check(condition, scope);
+                self.block(body, scope);
+                Type::Unknown
+            }
+            Instruction::FunctionDef { params, body, .. } => {
+                // The body may run after any of the outer variables have been
+                // reassigned, so nothing is known about them inside it.
+                let mut inner = scope
+                    .keys()
+                    .chain(params)
+                    .map(|name| (name.clone(), Type::Unknown))
+                    .collect();
+                self.statements(body, &mut inner);
+                Type::Unknown
+            }
+            Instruction::Return(value) => {
+                if let Some(value) = value {
+                    self.check(value, scope);
+                }
+                Type::Unknown
+            }
+            Instruction::Throw(value) => {
+                self.check(value, scope);
+                Type::Unknown
+            }
+            Instruction::Break | Instruction::Continue => Type::Unknown,
+            Instruction::ForBlock {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                let mut inner = scope.clone();
+                self.check(init, &mut inner);
+                self.check(condition, &mut inner);
+                // The body's own declarations, such as a `let` shadowing the
+                // loop variable, end before `update` runs.
+                self.block(body, &mut inner);
+                self.check(update, &mut inner);
+                merge(scope, &inner);
+                Type::Unknown
+            }
+            Instruction::TryCatch {
+                body,
+                param,
+                handler,
+            } => {
+                self.block(body, scope);
+                let mut inner = scope.clone();
+                inner.insert(param.clone(), Type::Unknown);
+                self.statements(handler, &mut inner);
+                merge(scope, &inner);
+                Type::Unknown
+            }
+        }
+    }
+
+    fn binary_op(
+        &mut self,
+        op: &BinOp,
+        lhs: Type,
+        rhs: Type,
+        span: &Range<usize>,
+    ) -> Type {
+        let ty = match (op, lhs, rhs) {
+            (BinOp::Eq | BinOp::Ne, _, _) => Some(Type::Bool),
+            (BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge, lhs, rhs) => {
+                match (lhs, rhs) {
+                    (Type::Unknown, _)
+                    | (_, Type::Unknown)
+                    | (Type::Int | Type::Float, Type::Int | Type::Float)
+                    | (Type::String, Type::String) => Some(Type::Bool),
+                    _ => None,
+                }
+            }
+            (_, Type::Unknown, _) | (_, _, Type::Unknown) => {
+                Some(Type::Unknown)
+            }
+            (BinOp::Add, Type::String, Type::String) => Some(Type::String),
+            (_, Type::Int, Type::Int) => Some(Type::Int),
+            (_, Type::Int | Type::Float, Type::Int | Type::Float) => {
+                Some(Type::Float)
+            }
+            _ => None,
+        };
+
+        ty.unwrap_or_else(|| {
+            self.error(
+                span,
+                format!("Cannot apply `{}` to {} and {}", op, lhs, rhs),
+            );
+            Type::Unknown
+        })
+    }
+
+    fn error(&mut self, span: &Range<usize>, message: impl ToString) {
+        self.errors.push(TypeError {
+            span: span.clone(),
+            message: message.to_string(),
+        });
+    }
+}
+
+/// Outer variables that `inner` gave a different type are `Unknown` once it
+/// ends, since the code that changed them might not have run.
+fn merge(scope: &mut Scope, inner: &Scope) {
+    for (name, ty) in scope.iter_mut() {
+        if inner.get(name) != Some(ty) {
+            *ty = Type::Unknown;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::parse;
+
+    fn check(src: &str) -> Result<(), Vec<TypeError>> {
+        typecheck(&parse(src).unwrap())
+    }
+
+    #[test]
+    fn well_typed() {
+        assert_eq!(
+            check(
+                "let x = 1 + 2.5;
+                let s = \"a\" + \"b\";
+                function f(a) { return a + 1; }
+                if (x < 4) { x = \"big\"; }
+                let y = x + 1;
+                let n = 0;
+                while (n < 3) { n = \"s\"; }
+                n - 1;
+                for (let i = 0; i < 3; i = i + 1) { let i = \"s\"; }"
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn string_plus_int() {
+        assert_eq!(
+            check("let x = \"a\" + 1;"),
+            Err(vec![TypeError {
+                span: 8..15,
+                message: "Cannot apply `+` to string and int".to_string(),
+            }])
+        );
+    }
+
+    #[test]
+    fn negated_string() {
+        assert_eq!(
+            check("let s = \"a\";\n-s;"),
+            Err(vec![TypeError {
+                span: 13..15,
+                message: "Cannot negate string".to_string(),
+            }])
+        );
+    }
+}
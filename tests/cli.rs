@@ -0,0 +1,71 @@
+use std::{
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+/// Runs the binary on `src`, saved under `name` so tests don't share a file.
+fn run(name: &str, src: &str, args: &[&str]) -> Output {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::write(&path, src).unwrap();
+
+    Command::new(env!("CARGO_BIN_EXE_trippy"))
+        .args(args)
+        .arg(path)
+        .output()
+        .unwrap()
+}
+
+fn exit_code(name: &str, src: &str, args: &[&str]) -> Option<i32> {
+    run(name, src, args).status.code()
+}
+
+#[test]
+fn success() {
+    assert_eq!(exit_code("success.ts", "console.log(1);", &[]), Some(0));
+}
+
+#[test]
+fn parse_error() {
+    assert_eq!(exit_code("parse_error.ts", "console.log(;", &[]), Some(1));
+    assert_eq!(
+        exit_code(
+            "parse_error.ts",
+            "console.log(;",
+            &["--pretty-errors=false"]
+        ),
+        Some(1)
+    );
+}
+
+#[test]
+fn type_error() {
+    assert_eq!(
+        exit_code("type_error.ts", "let x = \"a\" + 1;", &[]),
+        Some(2)
+    );
+}
+
+#[test]
+fn usage_error() {
+    assert_eq!(exit_code("usage.ts", "", &["--emit=svg"]), Some(64));
+    assert_eq!(exit_code("usage.ts", "", &["--pretty-errors=no"]), Some(64));
+
+    let no_file = Command::new(env!("CARGO_BIN_EXE_trippy")).output().unwrap();
+    assert_eq!(no_file.status.code(), Some(64));
+
+    let missing = Command::new(env!("CARGO_BIN_EXE_trippy"))
+        .arg("does-not-exist.ts")
+        .output()
+        .unwrap();
+    assert_eq!(missing.status.code(), Some(64));
+}
+
+#[test]
+fn dot_output_is_only_the_graph() {
+    let output = run("dot.ts", "let x = \"a\" + 1;", &["--emit=dot"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(stdout.starts_with("digraph ast {\n") && stdout.ends_with("}\n"));
+    assert!(stderr.contains("Cannot apply `+` to string and int"));
+}